@@ -0,0 +1,34 @@
+use std::{
+    collections::HashSet,
+    env,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+// Reads the bundled word list, normalizes it, and emits a `&[&str]` slice into OUT_DIR so
+// `main.rs` can embed it at compile time instead of requiring a dictionary file at runtime.
+fn main() {
+    println!("cargo:rerun-if-changed=words.txt");
+
+    let raw = fs::read_to_string("words.txt").expect("failed to read bundled word list");
+    let mut seen = HashSet::new();
+    let mut words: Vec<String> = Vec::new();
+    for line in raw.lines() {
+        let word = line.trim().to_lowercase();
+        if word.is_empty() || !seen.insert(word.clone()) {
+            continue;
+        }
+        words.push(word);
+    }
+
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR not set");
+    let dest_path = Path::new(&out_dir).join("dictionary.rs");
+    let mut out = File::create(&dest_path).expect("failed to create generated dictionary source");
+
+    writeln!(out, "static EMBEDDED_WORDS: &[&str] = &[").expect("failed to write generated dictionary source");
+    for word in &words {
+        writeln!(out, "    {:?},", word).expect("failed to write generated dictionary source");
+    }
+    writeln!(out, "];").expect("failed to write generated dictionary source");
+}
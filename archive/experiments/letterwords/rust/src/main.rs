@@ -1,9 +1,63 @@
 use std::{
+    collections::{BTreeMap, HashMap},
     fs::File,
-    io::{prelude::*, stdin, BufReader},
+    io::{prelude::*, stdin, stdout, BufReader, Write},
     path::Path,
 };
 
+use clap::Parser;
+use rand::{thread_rng, Rng};
+use rayon::prelude::*;
+
+// Word list bundled at compile time by build.rs; used unless `--dictionary` is given.
+include!(concat!(env!("OUT_DIR"), "/dictionary.rs"));
+
+/// Find phrases matching a word/letter count (optionally as an exact anagram), or generate a
+/// random diceware-style passphrase, from a dictionary word list.
+#[derive(Parser)]
+struct Args {
+    /// Path to the dictionary file, or "-" to read it from stdin. Defaults to the word list
+    /// embedded in the binary at compile time.
+    #[arg(long)]
+    dictionary: Option<String>,
+
+    /// Number of words in the phrase; prompted for interactively if omitted
+    #[arg(long)]
+    words: Option<u32>,
+
+    /// Number of letters in the phrase; prompted for interactively if omitted
+    #[arg(long)]
+    letters: Option<u32>,
+
+    /// Where to write results; defaults to stdout
+    #[arg(long)]
+    output: Option<String>,
+
+    /// Abort the search if the frontier's resident memory exceeds this many megabytes
+    #[arg(long = "max-rss-mb")]
+    max_rss_mb: Option<u64>,
+
+    /// Require phrases to be an exact anagram of these letters
+    #[arg(long)]
+    anagram: Option<String>,
+
+    /// Generate a random passphrase of this many words instead of searching
+    #[arg(long)]
+    generate: Option<usize>,
+
+    /// Separator placed between words in a generated passphrase
+    #[arg(long, default_value = "-")]
+    separator: String,
+
+    /// Re-roll a generated passphrase until it hits this many letters
+    #[arg(long = "target-letters")]
+    target_letters: Option<usize>,
+
+    /// Report dictionary word/letter statistics instead of searching or generating
+    #[arg(long)]
+    stats: bool,
+}
+
 fn read_int() -> u32 {
     let mut input_text = String::new();
     stdin()
@@ -25,48 +79,546 @@ fn lines_of_file(filename: impl AsRef<Path>) -> Vec<String> {
         .map(|line| line.expect("failed to read line"))
         .collect()
 }
-fn count_characters(string: &String) -> usize {
+
+fn load_dictionary(path: Option<&str>) -> Vec<String> {
+    match path {
+        Some("-") => stdin()
+            .lock()
+            .lines()
+            .map(|line| line.expect("failed to read line from stdin"))
+            .collect(),
+        Some(p) => lines_of_file(p),
+        None => EMBEDDED_WORDS.iter().map(|w| w.to_string()).collect(),
+    }
+}
+
+fn open_output(path: &Option<String>) -> Box<dyn Write> {
+    match path {
+        Some(p) => Box::new(File::create(p).expect("failed to create output file")),
+        None => Box::new(stdout()),
+    }
+}
+
+fn count_characters(string: &str) -> usize {
     string.chars().count() - string.matches(' ').count()
 }
 
-fn count_words(string: &String) -> usize {
+fn count_words(string: &str) -> usize {
     string.matches(' ').count() + 1
 }
 
-fn main() {
-    let words = lines_of_file("dictionary.csv");
-    let mut phrases = Vec::new();
-    println!("Input the number of words");
-    let word_count = read_int();
-    println!("Input the number of letters");
-    let letter_count = read_int();
-    let mut possibilities: Vec<String> = words
-        .to_vec()
-        .into_iter()
-        .filter(|w| w.len() <= (letter_count as usize) + 1 - (word_count as usize))
+// Result of expanding one candidate phrase by one more word.
+enum Expansion {
+    Done(String),
+    Next(String),
+}
+
+fn expand(check: &str, words: &[String], letter_count: usize, word_count: usize) -> Vec<Expansion> {
+    let cc = count_characters(check);
+    let wc = count_words(check);
+    if cc == letter_count && wc == word_count {
+        vec![Expansion::Done(check.to_string())]
+    } else if wc < word_count && cc < letter_count {
+        words
+            .iter()
+            .filter(|word| {
+                word.len() + cc < letter_count
+                    || (wc == word_count - 1 && word.len() + cc == letter_count)
+            })
+            .map(|word| Expansion::Next(format!("{} {}", check, word)))
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+fn current_rss_mb() -> Option<u64> {
+    memory_stats::memory_stats().map(|usage| usage.physical_mem as u64 / (1024 * 1024))
+}
+
+// Tracks the highest RSS seen so far and aborts the search if `max_rss_mb` is exceeded.
+fn track_rss(level: usize, peak_rss_mb: &mut u64, max_rss_mb: Option<u64>) {
+    if let Some(rss_mb) = current_rss_mb() {
+        *peak_rss_mb = (*peak_rss_mb).max(rss_mb);
+        if max_rss_mb.is_some_and(|max| rss_mb > max) {
+            eprintln!(
+                "aborting: frontier at level {} uses {}MB, over the --max-rss-mb budget of {}MB",
+                level,
+                rss_mb,
+                max_rss_mb.unwrap()
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+// Longest a single word can be and still leave at least one letter for every other word, or
+// `None` if `word_count` can't possibly fit in `letter_count` letters.
+fn max_first_word_len(word_count: usize, letter_count: usize) -> Option<usize> {
+    (letter_count + 1).checked_sub(word_count)
+}
+
+fn run_length_search(
+    words: &[String],
+    word_count: usize,
+    letter_count: usize,
+    max_rss_mb: Option<u64>,
+    out: &mut dyn Write,
+) {
+    let Some(max_first_word_len) = max_first_word_len(word_count, letter_count) else {
+        eprintln!(
+            "error: {} words can't fit in {} letters (need at least 1 letter per word)",
+            word_count, letter_count
+        );
+        std::process::exit(1);
+    };
+
+    let mut frontier: Vec<String> = words
+        .iter()
+        .filter(|w| w.len() <= max_first_word_len)
+        .cloned()
         .collect();
-    while possibilities.len() > 0 {
-        let check = possibilities.pop().expect("possibilities is empty!!");
-        //println!("{}", check);
-        let cc = count_characters(&check);
-        let wc = count_words(&check);
-        if cc == (letter_count as usize) && wc == (word_count as usize) {
-            phrases.push(check);
-        } else if wc < (word_count as usize) && cc < (letter_count as usize) {
-            for word in &words {
-                if word.len() + cc < (letter_count as usize)
-                    || (wc == (word_count as usize) - 1
-                        && word.len() + cc == (letter_count as usize))
-                {
-                    possibilities.push(format!("{} {}", check, word));
-                }
+
+    let mut phrases: Vec<String> = Vec::new();
+    let mut peak_rss_mb = 0u64;
+
+    for level in 1..=word_count {
+        if frontier.is_empty() {
+            break;
+        }
+
+        let expansions: Vec<Expansion> = frontier
+            .par_iter()
+            .flat_map(|check| expand(check, words, letter_count, word_count))
+            .collect();
+
+        frontier = Vec::with_capacity(expansions.len());
+        for expansion in expansions {
+            match expansion {
+                Expansion::Done(phrase) => phrases.push(phrase),
+                Expansion::Next(phrase) => frontier.push(phrase),
             }
         }
+
+        track_rss(level, &mut peak_rss_mb, max_rss_mb);
+
+        writeln!(
+            out,
+            "level {}: {} phrases in frontier, {} complete so far",
+            level,
+            frontier.len(),
+            phrases.len()
+        )
+        .expect("failed to write output");
     }
-    println!(
-        "{} - Possibilities for {} words and {} letters",
+
+    phrases.sort();
+
+    writeln!(
+        out,
+        "{} - Possibilities for {} words and {} letters (peak RSS {}MB)",
         phrases.len(),
         word_count,
-        letter_count
-    );
+        letter_count,
+        peak_rss_mb
+    )
+    .expect("failed to write output");
+}
+
+// A frequency table of the letters still available to spend on the rest of the phrase.
+fn letter_bag(s: &str) -> HashMap<char, u32> {
+    let mut bag = HashMap::new();
+    for c in s.chars().filter(|c| !c.is_whitespace()) {
+        *bag.entry(c.to_ascii_lowercase()).or_insert(0) += 1;
+    }
+    bag
+}
+
+fn word_fits(word: &str, remaining: &HashMap<char, u32>) -> bool {
+    let word_bag = letter_bag(word);
+    word_bag
+        .iter()
+        .all(|(c, n)| remaining.get(c).copied().unwrap_or(0) >= *n)
+}
+
+fn subtract_bag(remaining: &HashMap<char, u32>, word: &str) -> HashMap<char, u32> {
+    let mut next = remaining.clone();
+    for c in word.chars().filter(|c| !c.is_whitespace()) {
+        let c = c.to_ascii_lowercase();
+        if let Some(n) = next.get_mut(&c) {
+            *n -= 1;
+            if *n == 0 {
+                next.remove(&c);
+            }
+        }
+    }
+    next
+}
+
+#[derive(Clone)]
+struct AnagramCandidate {
+    phrase: String,
+    remaining: HashMap<char, u32>,
+    word_count: usize,
+}
+
+enum AnagramExpansion {
+    Done(String),
+    Next(AnagramCandidate),
+}
+
+fn expand_anagram(candidate: &AnagramCandidate, words: &[String], word_count: usize) -> Vec<AnagramExpansion> {
+    if candidate.remaining.is_empty() && candidate.word_count == word_count {
+        vec![AnagramExpansion::Done(candidate.phrase.clone())]
+    } else if candidate.word_count < word_count {
+        words
+            .iter()
+            .filter(|word| word_fits(word, &candidate.remaining))
+            .map(|word| {
+                AnagramExpansion::Next(AnagramCandidate {
+                    phrase: format!("{} {}", candidate.phrase, word),
+                    remaining: subtract_bag(&candidate.remaining, word),
+                    word_count: candidate.word_count + 1,
+                })
+            })
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+fn run_anagram_search(
+    words: &[String],
+    letters: &str,
+    word_count: usize,
+    max_rss_mb: Option<u64>,
+    out: &mut dyn Write,
+) {
+    let full_bag = letter_bag(letters);
+
+    let mut frontier: Vec<AnagramCandidate> = words
+        .iter()
+        .filter(|w| word_fits(w, &full_bag))
+        .map(|w| AnagramCandidate {
+            phrase: w.clone(),
+            remaining: subtract_bag(&full_bag, w),
+            word_count: 1,
+        })
+        .collect();
+
+    let mut phrases: Vec<String> = Vec::new();
+    let mut peak_rss_mb = 0u64;
+
+    for level in 1..=word_count {
+        if frontier.is_empty() {
+            break;
+        }
+
+        let expansions: Vec<AnagramExpansion> = frontier
+            .par_iter()
+            .flat_map(|candidate| expand_anagram(candidate, words, word_count))
+            .collect();
+
+        frontier = Vec::with_capacity(expansions.len());
+        for expansion in expansions {
+            match expansion {
+                AnagramExpansion::Done(phrase) => phrases.push(phrase),
+                AnagramExpansion::Next(candidate) => frontier.push(candidate),
+            }
+        }
+
+        track_rss(level, &mut peak_rss_mb, max_rss_mb);
+
+        writeln!(
+            out,
+            "level {}: {} phrases in frontier, {} complete so far",
+            level,
+            frontier.len(),
+            phrases.len()
+        )
+        .expect("failed to write output");
+    }
+
+    phrases.sort();
+
+    writeln!(
+        out,
+        "{} - Anagrams of \"{}\" using {} words (peak RSS {}MB)",
+        phrases.len(),
+        letters,
+        word_count,
+        peak_rss_mb
+    )
+    .expect("failed to write output");
+}
+
+// Picks `count` words independently and uniformly at random from the dictionary, re-rolling
+// the whole passphrase when `target_letters` is set and not yet hit, up to a retry cap.
+fn run_generate(
+    words: &[String],
+    count: usize,
+    separator: &str,
+    target_letters: Option<usize>,
+    out: &mut dyn Write,
+) {
+    if words.is_empty() {
+        eprintln!("error: can't generate a passphrase from an empty dictionary");
+        std::process::exit(1);
+    }
+
+    const RETRY_CAP: u32 = 1000;
+    let mut rng = thread_rng();
+
+    let mut chosen: Vec<String>;
+    let mut attempts = 0u32;
+    loop {
+        chosen = (0..count)
+            .map(|_| words[rng.gen_range(0..words.len())].clone())
+            .collect();
+        attempts += 1;
+
+        let Some(target) = target_letters else { break };
+        if count_characters(&chosen.join(" ")) == target || attempts >= RETRY_CAP {
+            break;
+        }
+    }
+
+    if target_letters.is_some_and(|target| count_characters(&chosen.join(" ")) != target) {
+        eprintln!(
+            "warning: could not hit target of {} letters after {} attempts, using closest attempt",
+            target_letters.unwrap(),
+            RETRY_CAP
+        );
+    }
+
+    let entropy_bits = count as f64 * (words.len() as f64).log2();
+    writeln!(out, "{}", chosen.join(separator)).expect("failed to write output");
+    writeln!(
+        out,
+        "entropy: {:.2} bits ({} words from a dictionary of {})",
+        entropy_bits,
+        count,
+        words.len()
+    )
+    .expect("failed to write output");
+}
+
+// Reports a length histogram plus, for a given word count, the range of total letter counts
+// that are reachable at all, so users can pick a viable `letter_count`/`word_count` pair.
+fn run_stats(words: &[String], word_count: Option<usize>, out: &mut dyn Write) {
+    let mut histogram: BTreeMap<usize, usize> = BTreeMap::new();
+    for word in words {
+        *histogram.entry(word.len()).or_insert(0) += 1;
+    }
+    let min_len = histogram.keys().next().copied().unwrap_or(0);
+    let max_len = histogram.keys().next_back().copied().unwrap_or(0);
+
+    writeln!(
+        out,
+        "{} words, length {}..={} letters",
+        words.len(),
+        min_len,
+        max_len
+    )
+    .expect("failed to write output");
+    for (len, count) in &histogram {
+        writeln!(out, "  {} letters: {} words", len, count).expect("failed to write output");
+    }
+
+    if let Some(word_count) = word_count.filter(|&n| n > 0) {
+        writeln!(
+            out,
+            "with {} words: reachable letter counts range {}..={}",
+            word_count,
+            min_len * word_count,
+            max_len * word_count
+        )
+        .expect("failed to write output");
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let words = load_dictionary(args.dictionary.as_deref());
+    let mut out = open_output(&args.output);
+
+    if args.stats {
+        run_stats(&words, args.words.map(|n| n as usize), &mut out);
+        return;
+    }
+
+    if let Some(count) = args.generate {
+        run_generate(&words, count, &args.separator, args.target_letters, &mut out);
+        return;
+    }
+
+    let word_count = match args.words {
+        Some(n) => n as usize,
+        None => {
+            println!("Input the number of words");
+            read_int() as usize
+        }
+    };
+
+    if let Some(letters) = args.anagram {
+        run_anagram_search(&words, &letters, word_count, args.max_rss_mb, &mut out);
+    } else {
+        let letter_count = match args.letters {
+            Some(n) => n as usize,
+            None => {
+                println!("Input the number of letters");
+                read_int() as usize
+            }
+        };
+        run_length_search(&words, word_count, letter_count, args.max_rss_mb, &mut out);
+    }
+}
+
+#[cfg(test)]
+mod length_tests {
+    use super::*;
+
+    #[test]
+    fn max_first_word_len_is_none_when_word_count_exceeds_letter_count_plus_one() {
+        // 5 words need at least 5 letters, but only 2 are budgeted; `run_length_search` takes
+        // this as its cue to print an error and exit rather than silently doing nothing.
+        assert_eq!(max_first_word_len(5, 2), None);
+    }
+
+    #[test]
+    fn run_length_search_finds_the_correct_phrase_set() {
+        // Of the 3*3 ordered pairs drawn from {"a", "ab", "b"}, exactly the 4 pairs whose
+        // lengths sum to 3 survive: "a ab", "ab a", "ab b", "b ab".
+        let words = vec!["a".to_string(), "ab".to_string(), "b".to_string()];
+        let mut out = Vec::new();
+        run_length_search(&words, 2, 3, None, &mut out);
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("4 - Possibilities for 2 words and 3 letters"));
+    }
+
+    #[test]
+    fn run_length_search_finds_nothing_when_no_combination_fits() {
+        let words = vec!["abcdef".to_string()];
+        let mut out = Vec::new();
+        run_length_search(&words, 2, 3, None, &mut out);
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("0 - Possibilities for 2 words and 3 letters"));
+    }
+}
+
+#[cfg(test)]
+mod anagram_tests {
+    use super::*;
+
+    #[test]
+    fn word_fits_accepts_a_word_within_budget() {
+        let remaining = letter_bag("listen");
+        assert!(word_fits("silent", &remaining));
+    }
+
+    #[test]
+    fn word_fits_rejects_a_letter_deficit() {
+        let remaining = letter_bag("cat");
+        assert!(!word_fits("cats", &remaining));
+    }
+
+    #[test]
+    fn subtract_bag_removes_used_letters() {
+        let remaining = letter_bag("listen");
+        let next = subtract_bag(&remaining, "sent");
+        assert_eq!(next.get(&'s'), None);
+        assert_eq!(next.get(&'l'), Some(&1));
+    }
+
+    #[test]
+    fn run_anagram_search_only_counts_exact_anagrams() {
+        // "cat" + "dog" consumes exactly the "catdog" bag in either order; "cat cat" or
+        // "dog dog" would each need letters the bag doesn't have enough of.
+        let words = vec!["cat".to_string(), "dog".to_string()];
+        let mut out = Vec::new();
+        run_anagram_search(&words, "catdog", 2, None, &mut out);
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("2 - Anagrams of \"catdog\" using 2 words"));
+    }
+
+    #[test]
+    fn run_anagram_search_finds_nothing_when_letters_dont_match() {
+        let words = vec!["cat".to_string(), "dog".to_string()];
+        let mut out = Vec::new();
+        run_anagram_search(&words, "xyz", 1, None, &mut out);
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("0 - Anagrams of \"xyz\" using 1 words"));
+    }
+}
+
+#[cfg(test)]
+mod generate_tests {
+    use super::*;
+
+    #[test]
+    fn generate_retries_until_the_target_letter_count_is_hit() {
+        let words = vec!["ab".to_string(), "abcd".to_string()];
+        let mut out = Vec::new();
+        run_generate(&words, 2, "-", Some(4), &mut out);
+        let passphrase = String::from_utf8(out).unwrap().lines().next().unwrap().to_string();
+        let total_letters: usize = passphrase.split('-').map(|w| w.len()).sum();
+        assert_eq!(total_letters, 4);
+    }
+
+    #[test]
+    fn generate_gives_up_after_the_retry_cap_for_an_unreachable_target() {
+        // Every word is 2 letters, so 2 words can never total an odd letter count.
+        let words = vec!["ab".to_string(), "cd".to_string()];
+        let mut out = Vec::new();
+        run_generate(&words, 2, "-", Some(99), &mut out);
+        let passphrase = String::from_utf8(out).unwrap().lines().next().unwrap().to_string();
+        assert_eq!(passphrase.split('-').count(), 2);
+    }
+
+    #[test]
+    fn generate_reports_entropy_as_words_times_log2_of_dictionary_len() {
+        let words = vec!["a".to_string(), "b".to_string()];
+        let mut out = Vec::new();
+        run_generate(&words, 3, "-", None, &mut out);
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("entropy: 3.00 bits (3 words from a dictionary of 2)"));
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+
+    #[test]
+    fn stats_reports_a_length_histogram_and_bounds() {
+        let words = vec!["ab".to_string(), "abc".to_string(), "de".to_string()];
+        let mut out = Vec::new();
+        run_stats(&words, None, &mut out);
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("3 words, length 2..=3 letters"));
+        assert!(output.contains("2 letters: 2 words"));
+        assert!(output.contains("3 letters: 1 words"));
+    }
+
+    #[test]
+    fn stats_reports_the_reachable_letter_range_for_a_word_count() {
+        let words = vec!["ab".to_string(), "abcd".to_string()];
+        let mut out = Vec::new();
+        run_stats(&words, Some(3), &mut out);
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("with 3 words: reachable letter counts range 6..=12"));
+    }
+
+    #[test]
+    fn stats_omits_the_reachable_range_when_word_count_is_zero_or_absent() {
+        let words = vec!["ab".to_string()];
+
+        let mut with_zero = Vec::new();
+        run_stats(&words, Some(0), &mut with_zero);
+        assert!(!String::from_utf8(with_zero).unwrap().contains("reachable letter counts"));
+
+        let mut without_count = Vec::new();
+        run_stats(&words, None, &mut without_count);
+        assert!(!String::from_utf8(without_count).unwrap().contains("reachable letter counts"));
+    }
 }